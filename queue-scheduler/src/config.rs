@@ -0,0 +1,124 @@
+use std::env;
+
+pub struct DatabaseConfig {
+    pub url: String,
+    // number of pooled connections available to worker threads
+    pub pool_size: u32,
+}
+
+pub struct Config {
+    pub database: DatabaseConfig,
+    // how long the listener blocks waiting for a NOTIFY before giving up on
+    // that poll and checking whether a reconcile is due
+    pub listen_timeout_secs: u64,
+    // how often the listener runs a reconciling COUNT(*)-and-claim pass as a
+    // safety net, in case a NOTIFY was missed
+    pub reconcile_interval_secs: u64,
+    // max number of events a single worker claims per pass
+    pub batch_size: i64,
+    // how long a row can sit in `processing` before the reaper assumes its
+    // worker crashed and releases it back to `new`
+    pub processing_timeout_secs: u64,
+    // retry backoff is base_delay * 2^attempts, capped at max_delay_secs
+    pub base_delay_secs: u64,
+    pub max_delay_secs: u64,
+    // rows that fail more than this many times move to the `dead` status
+    pub max_retries: i32,
+    // how often the metrics reporter logs queue depth/throughput/latency
+    pub metrics_report_interval_secs: u64,
+    // port the /metrics HTTP endpoint listens on; unset disables it
+    pub metrics_port: Option<u16>,
+    // connections reserved for the metrics reporter and /metrics endpoint,
+    // kept separate from `database.pool_size` so a busy worker pool can
+    // never starve them out
+    pub metrics_pool_size: u32,
+}
+
+fn require_str(name: &str) -> String {
+    env::var(name).unwrap_or_else(|_| panic!("{} must be set", name))
+}
+
+fn optional_u64(name: &str, default: u64) -> u64 {
+    env::var(name)
+        .ok()
+        .map(|v| {
+            v.parse()
+                .unwrap_or_else(|_| panic!("{} must be a valid integer", name))
+        })
+        .unwrap_or(default)
+}
+
+fn optional_u32(name: &str, default: u32) -> u32 {
+    env::var(name)
+        .ok()
+        .map(|v| {
+            v.parse()
+                .unwrap_or_else(|_| panic!("{} must be a valid integer", name))
+        })
+        .unwrap_or(default)
+}
+
+fn optional_i64(name: &str, default: i64) -> i64 {
+    env::var(name)
+        .ok()
+        .map(|v| {
+            v.parse()
+                .unwrap_or_else(|_| panic!("{} must be a valid integer", name))
+        })
+        .unwrap_or(default)
+}
+
+fn optional_i32(name: &str, default: i32) -> i32 {
+    env::var(name)
+        .ok()
+        .map(|v| {
+            v.parse()
+                .unwrap_or_else(|_| panic!("{} must be a valid integer", name))
+        })
+        .unwrap_or(default)
+}
+
+fn optional_port(name: &str) -> Option<u16> {
+    env::var(name)
+        .ok()
+        .map(|v| {
+            v.parse()
+                .unwrap_or_else(|_| panic!("{} must be a valid port", name))
+        })
+        .filter(|port| *port != 0)
+}
+
+pub fn load() -> Result<Config, env::VarError> {
+    Ok(Config {
+        database: DatabaseConfig {
+            url: require_str("DARK_CONFIG_DB_URL"),
+            pool_size: optional_u32("DARK_CONFIG_QUEUE_POOL_SIZE", 4),
+        },
+        listen_timeout_secs: optional_u64("DARK_CONFIG_QUEUE_LISTEN_TIMEOUT_SECS", 5),
+        reconcile_interval_secs: optional_u64("DARK_CONFIG_QUEUE_RECONCILE_INTERVAL_SECS", 5),
+        batch_size: optional_i64("DARK_CONFIG_QUEUE_BATCH_SIZE", 10),
+        processing_timeout_secs: optional_u64("DARK_CONFIG_QUEUE_PROCESSING_TIMEOUT_SECS", 300),
+        base_delay_secs: optional_u64("DARK_CONFIG_QUEUE_BASE_DELAY_SECS", 1),
+        max_delay_secs: optional_u64("DARK_CONFIG_QUEUE_MAX_DELAY_SECS", 3600),
+        max_retries: optional_i32("DARK_CONFIG_QUEUE_MAX_RETRIES", 10),
+        metrics_report_interval_secs: optional_u64("DARK_CONFIG_QUEUE_METRICS_INTERVAL_SECS", 10),
+        metrics_port: optional_port("DARK_CONFIG_QUEUE_METRICS_PORT"),
+        metrics_pool_size: optional_u32("DARK_CONFIG_QUEUE_METRICS_POOL_SIZE", 2),
+    })
+}
+
+pub fn pusher_app_id() -> String {
+    require_str("DARK_CONFIG_PUSHER_APP_ID")
+}
+
+pub fn pusher_key() -> String {
+    require_str("DARK_CONFIG_PUSHER_KEY")
+}
+
+pub fn pusher_secret() -> String {
+    require_str("DARK_CONFIG_PUSHER_SECRET")
+}
+
+pub fn pusher_host() -> String {
+    require_str("DARK_CONFIG_PUSHER_HOST")
+}
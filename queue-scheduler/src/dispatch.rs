@@ -0,0 +1,137 @@
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac, NewMac};
+use lazy_static::lazy_static;
+use sha2::Sha256;
+use slog::{error, info};
+
+use crate::config;
+
+type HmacSha256 = Hmac<Sha256>;
+
+lazy_static! {
+    // reused across calls so each publish doesn't pay for a fresh
+    // connection pool/TLS handshake
+    static ref CLIENT: reqwest::blocking::Client = reqwest::blocking::Client::new();
+}
+
+#[derive(Debug)]
+pub struct DispatchError(String);
+
+impl fmt::Display for DispatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// Builds the `auth_key=...&auth_timestamp=...&auth_version=1.0&body_md5=...`
+// query string Pusher signs over, split out so it's testable without a body
+// that needs signing.
+fn auth_query(key: &str, auth_timestamp: u64, body_md5: &str) -> String {
+    format!(
+        "auth_key={}&auth_timestamp={}&auth_version=1.0&body_md5={}",
+        key, auth_timestamp, body_md5
+    )
+}
+
+fn canonical_string(method: &str, path: &str, query: &str) -> String {
+    format!("{}\n{}\n{}", method, path, query)
+}
+
+fn sign(secret: &str, canonical_string: &str) -> Result<String, DispatchError> {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes())
+        .map_err(|e| DispatchError(e.to_string()))?;
+    mac.update(canonical_string.as_bytes());
+    Ok(hex::encode(mac.finalize().into_bytes()))
+}
+
+// Publishes `payload` to a Pusher channel, signing the request per
+// https://pusher.com/docs/channels/library_auth_reference/rest-api#authentication
+pub fn publish(
+    log: &slog::Logger,
+    channel: &str,
+    event_name: &str,
+    payload: &serde_json::Value,
+) -> Result<(), DispatchError> {
+    let app_id = config::pusher_app_id();
+    let key = config::pusher_key();
+    let secret = config::pusher_secret();
+    let host = config::pusher_host();
+
+    let body = serde_json::json!({
+        "name": event_name,
+        "channel": channel,
+        "data": payload.to_string(),
+    })
+    .to_string();
+
+    let path = format!("/apps/{}/events", app_id);
+    let auth_timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| DispatchError(e.to_string()))?
+        .as_secs();
+    let body_md5 = format!("{:x}", md5::compute(&body));
+
+    let query = auth_query(&key, auth_timestamp, &body_md5);
+    let canonical = canonical_string("POST", &path, &query);
+    let auth_signature = sign(&secret, &canonical)?;
+
+    let url = format!("{}{}?{}&auth_signature={}", host, path, query, auth_signature);
+
+    let response = CLIENT
+        .post(&url)
+        .header("Content-Type", "application/json")
+        .body(body)
+        .send()
+        .map_err(|e| DispatchError(e.to_string()))?;
+
+    if response.status().is_success() {
+        info!(log, "pusher_publish" ; "channel" => channel, "event" => event_name);
+        Ok(())
+    } else {
+        let status = response.status();
+        error!(log, "pusher_publish_failed" ; "channel" => channel, "event" => event_name, "status" => status.as_u16());
+        Err(DispatchError(format!("pusher responded with {}", status)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Request body built the same way publish() builds it, with a fixed
+    // timestamp, checked against independently-computed md5/HMAC-SHA256
+    // values so a change to the canonical string or signing logic is caught.
+    #[test]
+    fn signs_canonical_string_matching_known_vector() {
+        let secret = "7ad3773142a6692b25b8";
+        let key = "278d425bdf160c739803";
+        let app_id = "3";
+        let auth_timestamp = 1_353_088_179u64;
+
+        let body = serde_json::json!({
+            "name": "foo",
+            "channel": "project-3",
+            "data": serde_json::json!({"some": "data"}).to_string(),
+        })
+        .to_string();
+        let body_md5 = format!("{:x}", md5::compute(&body));
+        assert_eq!(body_md5, "fbf1828fadd91f29fee3153af382a61e");
+
+        let path = format!("/apps/{}/events", app_id);
+        let query = auth_query(key, auth_timestamp, &body_md5);
+        let canonical = canonical_string("POST", &path, &query);
+        assert_eq!(
+            canonical,
+            "POST\n/apps/3/events\nauth_key=278d425bdf160c739803&auth_timestamp=1353088179&\
+             auth_version=1.0&body_md5=fbf1828fadd91f29fee3153af382a61e"
+        );
+
+        let signature = sign(secret, &canonical).unwrap();
+        assert_eq!(
+            signature,
+            "9a26d8115ce84296236ab18c93af934f7cf76534359064bb48b220d11cc6fc15"
+        );
+    }
+}
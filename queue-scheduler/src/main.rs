@@ -1,11 +1,25 @@
-use std::sync::Mutex;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time;
+use std::time::Duration;
 
+use r2d2_postgres::{PostgresConnectionManager, TlsMode};
 use slog::Drain; // allow treating Mutex as a Drain
-use slog::{info, o}; // macros
+use slog::{error, info, o}; // macros
 
 mod config;
+mod dispatch;
+mod metrics;
+mod queue;
+
+#[derive(Clone, Copy)]
+struct RetryPolicy {
+    base_delay_secs: u64,
+    max_delay_secs: u64,
+    max_retries: i32,
+}
 
 fn main() {
     let t_start = time::Instant::now();
@@ -32,14 +46,177 @@ fn main() {
     );
 
     let cfg = config::load().unwrap();
-    let conn = postgres::Connection::connect(cfg.database.url, postgres::TlsMode::None).unwrap();
-
-    loop {
-        thread::sleep(time::Duration::from_secs(1));
-        let rows = conn
-            .query("SELECT COUNT(*) FROM events WHERE status = 'new'", &[])
-            .unwrap();
-        let count: i64 = rows.get(0).get(0);
-        info!(log, "tick" ; "new_events.count" => count);
+
+    let manager =
+        PostgresConnectionManager::new(cfg.database.url.clone(), TlsMode::None).unwrap();
+    let pool = r2d2::Pool::builder()
+        .max_size(cfg.database.pool_size)
+        .build(manager)
+        .unwrap();
+
+    // the metrics reporter and /metrics endpoint get their own small pool so
+    // they never queue behind workers for a connection under load
+    let metrics_manager =
+        PostgresConnectionManager::new(cfg.database.url.clone(), TlsMode::None).unwrap();
+    let metrics_pool = r2d2::Pool::builder()
+        .max_size(cfg.metrics_pool_size)
+        .build(metrics_manager)
+        .unwrap();
+
+    // workers block on this channel and wake up to drain the queue whenever
+    // the listener below sees a NOTIFY or its reconcile timer fires.
+    // crossbeam_channel's Receiver is Sync and takes &self in recv(), so every
+    // worker can block on it at once instead of taking turns for a lock.
+    let (wake_tx, wake_rx) = crossbeam_channel::unbounded::<()>();
+
+    let running = Arc::new(AtomicBool::new(true));
+    {
+        let running = Arc::clone(&running);
+        ctrlc::set_handler(move || running.store(false, Ordering::SeqCst))
+            .expect("failed to install SIGINT/SIGTERM handler");
+    }
+
+    // events a worker has claimed but not yet finished dispatching, so
+    // shutdown can report how much work it's draining before it exits
+    let in_flight = Arc::new(AtomicI64::new(0));
+
+    let metrics = Arc::new(metrics::Metrics::new());
+    {
+        let metrics_pool = metrics_pool.clone();
+        let log = log.clone();
+        let metrics = Arc::clone(&metrics);
+        let interval = Duration::from_secs(cfg.metrics_report_interval_secs);
+        thread::spawn(move || metrics::report_periodically(metrics_pool, log, metrics, interval));
+    }
+    if let Some(port) = cfg.metrics_port {
+        metrics::serve(port, metrics_pool, Arc::clone(&metrics));
+    }
+
+    let batch_size = cfg.batch_size;
+    let processing_timeout_secs = cfg.processing_timeout_secs;
+    let retry_policy = RetryPolicy {
+        base_delay_secs: cfg.base_delay_secs,
+        max_delay_secs: cfg.max_delay_secs,
+        max_retries: cfg.max_retries,
+    };
+
+    let mut workers = Vec::new();
+    for worker_id in 0..cfg.database.pool_size {
+        let pool = pool.clone();
+        let log = log.clone();
+        let wake_rx = wake_rx.clone();
+        let in_flight = Arc::clone(&in_flight);
+        let metrics = Arc::clone(&metrics);
+        let running = Arc::clone(&running);
+        workers.push(thread::spawn(move || loop {
+            if wake_rx.recv().is_err() {
+                break;
+            }
+            let conn = pool.get().unwrap();
+            tick(
+                &conn,
+                &log,
+                worker_id,
+                batch_size,
+                retry_policy,
+                &in_flight,
+                &metrics,
+                &running,
+            );
+        }));
+    }
+
+    // the listener keeps its own unpooled connection so LISTEN/NOTIFY never
+    // has to compete with workers for a pooled slot
+    let listener_conn =
+        postgres::Connection::connect(cfg.database.url, postgres::TlsMode::None).unwrap();
+    listener_conn.execute("LISTEN new_events", &[]).unwrap();
+    let notifications = listener_conn.notifications();
+    let mut incoming = notifications.timeout_iter(Duration::from_secs(cfg.listen_timeout_secs));
+    let reconcile_interval = Duration::from_secs(cfg.reconcile_interval_secs);
+
+    let mut last_reconcile = time::Instant::now();
+    wake_tx.send(()).unwrap();
+
+    while running.load(Ordering::SeqCst) {
+        match incoming.next() {
+            Some(Ok(_notification)) => wake_tx.send(()).unwrap(),
+            Some(Err(e)) => info!(log, "notification_error" ; "error" => e.to_string()),
+            // timed out waiting for a NOTIFY; the reconcile check below covers us
+            None => {}
+        }
+
+        if last_reconcile.elapsed() >= reconcile_interval {
+            let reaped = queue::reap_stale(&listener_conn, processing_timeout_secs);
+            if reaped > 0 {
+                info!(log, "reaped_stale_events" ; "count" => reaped);
+            }
+            wake_tx.send(()).unwrap();
+            last_reconcile = time::Instant::now();
+        }
+    }
+
+    // stop waking workers for new work, then let whatever they already
+    // claimed finish before we join them
+    drop(wake_tx);
+    info!(log, "shutting_down" ; "in_flight.count" => in_flight.load(Ordering::SeqCst));
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    // the logger has no reachable flush of its own (its Drain is a Mutex over
+    // a Json writer), so flush the stdout it writes to directly to make sure
+    // the last log lines above made it out before the process exits
+    std::io::stdout().flush().ok();
+}
+
+// Drains the queue in `batch_size` chunks until a claim comes back empty, so
+// a single wake-up (one NOTIFY, or one reconcile tick) fully recovers a
+// backlog instead of only chipping `batch_size` rows off it. Checked at the
+// top of every iteration so a worker that wakes after shutdown was
+// requested won't claim fresh work, even though it still finishes whatever
+// it already claimed this pass.
+fn tick(
+    conn: &postgres::Connection,
+    log: &slog::Logger,
+    worker_id: u32,
+    batch_size: i64,
+    retry_policy: RetryPolicy,
+    in_flight: &AtomicI64,
+    metrics: &metrics::Metrics,
+    running: &AtomicBool,
+) {
+    while running.load(Ordering::SeqCst) {
+        let claimed = queue::claim(conn, batch_size);
+        if claimed.is_empty() {
+            break;
+        }
+
+        info!(log, "tick" ; "worker" => worker_id, "claimed.count" => claimed.len() as i64);
+        in_flight.fetch_add(claimed.len() as i64, Ordering::SeqCst);
+
+        for event in claimed {
+            match dispatch::publish(log, &event.channel, &event.event_name, &event.payload) {
+                Ok(()) => queue::mark_done(conn, event.id),
+                Err(e) => {
+                    error!(log, "event_dispatch_failed" ; "id" => event.id, "error" => e.to_string());
+                    queue::mark_failed(
+                        conn,
+                        event.id,
+                        retry_policy.base_delay_secs,
+                        retry_policy.max_delay_secs,
+                        retry_policy.max_retries,
+                    );
+                }
+            }
+
+            let latency_us = (chrono::Utc::now() - event.created_at)
+                .num_microseconds()
+                .unwrap_or(0)
+                .max(0) as u64;
+            metrics.record_latency(latency_us);
+
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+        }
     }
-}
\ No newline at end of file
+}
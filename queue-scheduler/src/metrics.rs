@@ -0,0 +1,117 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use hdrhistogram::Histogram;
+use r2d2_postgres::PostgresConnectionManager;
+use slog::info;
+
+use crate::queue;
+
+// Tracks per-event processing latency (created_at -> completion) and a
+// running count of events processed, so the periodic report and /metrics
+// endpoint below have something to show beyond the `tick` log line.
+pub struct Metrics {
+    latency_us: Mutex<Histogram<u64>>,
+    processed_total: AtomicU64,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics {
+            // 1us to 1 hour, 3 significant figures
+            latency_us: Mutex::new(Histogram::new_with_bounds(1, 3_600_000_000, 3).unwrap()),
+            processed_total: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record_latency(&self, latency_us: u64) {
+        self.latency_us.lock().unwrap().record(latency_us).ok();
+        self.processed_total.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn processed_total(&self) -> u64 {
+        self.processed_total.load(Ordering::SeqCst)
+    }
+
+    // (p50, p95, p99) in microseconds
+    pub fn percentiles(&self) -> (u64, u64, u64) {
+        let hist = self.latency_us.lock().unwrap();
+        (
+            hist.value_at_quantile(0.50),
+            hist.value_at_quantile(0.95),
+            hist.value_at_quantile(0.99),
+        )
+    }
+}
+
+// Periodically logs queue depth by status, processed/sec, and latency
+// percentiles. Runs on the calling thread; spawn it if you want it in the
+// background.
+pub fn report_periodically(
+    pool: r2d2::Pool<PostgresConnectionManager>,
+    log: slog::Logger,
+    metrics: Arc<Metrics>,
+    interval: std::time::Duration,
+) {
+    let mut last_processed_total = metrics.processed_total();
+    loop {
+        thread::sleep(interval);
+
+        let conn = pool.get().unwrap();
+        let depth = queue::depth_by_status(&conn);
+        let (p50, p95, p99) = metrics.percentiles();
+
+        let processed_total = metrics.processed_total();
+        let processed_per_sec =
+            (processed_total - last_processed_total) as f64 / interval.as_secs_f64();
+        last_processed_total = processed_total;
+
+        info!(log, "queue_metrics" ;
+            "queue_depth" => format!("{:?}", depth),
+            "processed_per_sec" => processed_per_sec,
+            "latency_us.p50" => p50,
+            "latency_us.p95" => p95,
+            "latency_us.p99" => p99,
+        );
+    }
+}
+
+// Serves a tiny Prometheus-style /metrics endpoint on `port`, in a
+// background thread.
+pub fn serve(port: u16, pool: r2d2::Pool<PostgresConnectionManager>, metrics: Arc<Metrics>) {
+    let server = tiny_http::Server::http(("0.0.0.0", port)).unwrap();
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let conn = pool.get().unwrap();
+            let depth = queue::depth_by_status(&conn);
+            let (p50, p95, p99) = metrics.percentiles();
+
+            let mut body = String::new();
+            for (status, count) in depth {
+                body.push_str(&format!(
+                    "queue_scheduler_queue_depth{{status=\"{}\"}} {}\n",
+                    status, count
+                ));
+            }
+            body.push_str(&format!(
+                "queue_scheduler_processed_total {}\n",
+                metrics.processed_total()
+            ));
+            body.push_str(&format!(
+                "queue_scheduler_latency_microseconds{{quantile=\"0.5\"}} {}\n",
+                p50
+            ));
+            body.push_str(&format!(
+                "queue_scheduler_latency_microseconds{{quantile=\"0.95\"}} {}\n",
+                p95
+            ));
+            body.push_str(&format!(
+                "queue_scheduler_latency_microseconds{{quantile=\"0.99\"}} {}\n",
+                p99
+            ));
+
+            let _ = request.respond(tiny_http::Response::from_string(body));
+        }
+    });
+}
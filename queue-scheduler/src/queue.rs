@@ -0,0 +1,171 @@
+use chrono::{DateTime, Utc};
+use postgres::Connection;
+
+pub struct ClaimedEvent {
+    pub id: i64,
+    pub channel: String,
+    pub event_name: String,
+    pub payload: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+// Atomically claims up to `batch` new events. FOR UPDATE SKIP LOCKED lets
+// concurrent workers (or scheduler instances) each grab a disjoint set
+// without blocking on rows another worker already has locked. Marking the
+// rows `processing` before COMMIT means they're visible as claimed before
+// the (potentially slow) dispatch work even starts.
+pub fn claim(conn: &Connection, batch: i64) -> Vec<ClaimedEvent> {
+    let txn = conn.transaction().unwrap();
+
+    let rows = txn
+        .query(
+            "SELECT id, channel, event_name, payload, created_at FROM events \
+             WHERE status = 'new' AND run_at <= now() ORDER BY created_at \
+             FOR UPDATE SKIP LOCKED LIMIT $1",
+            &[&batch],
+        )
+        .unwrap();
+
+    let claimed: Vec<ClaimedEvent> = rows
+        .iter()
+        .map(|row| ClaimedEvent {
+            id: row.get(0),
+            channel: row.get(1),
+            event_name: row.get(2),
+            payload: row.get(3),
+            created_at: row.get(4),
+        })
+        .collect();
+
+    if !claimed.is_empty() {
+        let ids: Vec<i64> = claimed.iter().map(|e| e.id).collect();
+        txn.execute(
+            "UPDATE events SET status = 'processing', locked_at = now() WHERE id = ANY($1)",
+            &[&ids],
+        )
+        .unwrap();
+    }
+
+    txn.commit().unwrap();
+    claimed
+}
+
+pub fn mark_done(conn: &Connection, id: i64) {
+    conn.execute("UPDATE events SET status = 'done' WHERE id = $1", &[&id])
+        .unwrap();
+}
+
+// What should happen to a row after a failed dispatch, decided purely from
+// its attempt count so the arithmetic can be tested without a database.
+#[derive(Debug, PartialEq)]
+enum RetryOutcome {
+    Reschedule { delay_secs: u64 },
+    Dead,
+}
+
+// `attempts` is the count *after* this failure has been counted. Backoff is
+// base_delay * 2^attempts, capped at max_delay_secs; past max_retries the row
+// is terminal instead of rescheduled.
+fn retry_outcome(
+    attempts: i32,
+    base_delay_secs: u64,
+    max_delay_secs: u64,
+    max_retries: i32,
+) -> RetryOutcome {
+    if attempts > max_retries {
+        return RetryOutcome::Dead;
+    }
+
+    let exponent = attempts.max(0).min(32) as u32;
+    let delay_secs = base_delay_secs
+        .checked_mul(1u64 << exponent)
+        .unwrap_or(u64::MAX)
+        .min(max_delay_secs);
+
+    RetryOutcome::Reschedule { delay_secs }
+}
+
+// Bumps `attempts` and either reschedules the row with an exponentially
+// backed-off `run_at`, or, past `max_retries`, moves it to the terminal
+// `dead` status so it stops being retried.
+pub fn mark_failed(
+    conn: &Connection,
+    id: i64,
+    base_delay_secs: u64,
+    max_delay_secs: u64,
+    max_retries: i32,
+) {
+    let rows = conn
+        .query(
+            "UPDATE events SET attempts = attempts + 1 WHERE id = $1 RETURNING attempts",
+            &[&id],
+        )
+        .unwrap();
+    let attempts: i32 = rows.get(0).get(0);
+
+    match retry_outcome(attempts, base_delay_secs, max_delay_secs, max_retries) {
+        RetryOutcome::Dead => {
+            conn.execute("UPDATE events SET status = 'dead' WHERE id = $1", &[&id])
+                .unwrap();
+        }
+        RetryOutcome::Reschedule { delay_secs } => {
+            conn.execute(
+                "UPDATE events SET status = 'new', run_at = now() + ($2 * interval '1 second') \
+                 WHERE id = $1",
+                &[&id, &(delay_secs as i64)],
+            )
+            .unwrap();
+        }
+    }
+}
+
+// Counts rows per status, e.g. `new`, `processing`, `done`, `failed`, `dead`.
+pub fn depth_by_status(conn: &Connection) -> Vec<(String, i64)> {
+    conn.query("SELECT status, COUNT(*) FROM events GROUP BY status", &[])
+        .unwrap()
+        .iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect()
+}
+
+// Releases rows stuck in `processing` past `timeout_secs` back to `new` so a
+// crashed worker doesn't strand them there forever. Returns the number of
+// rows released.
+pub fn reap_stale(conn: &Connection, timeout_secs: u64) -> u64 {
+    conn.execute(
+        "UPDATE events SET status = 'new', locked_at = NULL \
+         WHERE status = 'processing' AND locked_at < now() - ($1 * interval '1 second')",
+        &[&(timeout_secs as i64)],
+    )
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reschedules_with_exponential_backoff() {
+        assert_eq!(
+            retry_outcome(1, 1, 3600, 10),
+            RetryOutcome::Reschedule { delay_secs: 2 }
+        );
+        assert_eq!(
+            retry_outcome(3, 1, 3600, 10),
+            RetryOutcome::Reschedule { delay_secs: 8 }
+        );
+    }
+
+    #[test]
+    fn caps_delay_at_max_delay_secs() {
+        assert_eq!(
+            retry_outcome(20, 1, 3600, 30),
+            RetryOutcome::Reschedule { delay_secs: 3600 }
+        );
+    }
+
+    #[test]
+    fn moves_to_dead_past_max_retries() {
+        assert_eq!(retry_outcome(11, 1, 3600, 10), RetryOutcome::Dead);
+    }
+}